@@ -0,0 +1,271 @@
+use indexmap::map::IndexMap;
+
+use crate::interaction_net::normalize;
+use crate::syntax::{Term, Var};
+use crate::types::{moves, Type, TypeError};
+use crate::verilog_ir::{Polarity, VConn, VModule, VPort, VPortLoc};
+
+/// A primitive's type together with the `VModule` that implements it. The
+/// module's `interfaces` must be declared in the same order as `[|ty|]`
+/// (see [`crate::types::moves`]) -- `compile` trusts that order when wiring
+/// an instance's ports to the rest of the circuit.
+#[derive(Clone)]
+pub struct PrimSig {
+    pub ty: Type,
+    pub module: VModule,
+}
+
+pub type Signature = IndexMap<String, PrimSig>;
+
+/// A bound variable's type together with where its moves currently live.
+type VarEnv = IndexMap<Var, (Type, Vec<VPortLoc>)>;
+
+/// Bookkeeping threaded through compilation: every `Prim` use instantiates a
+/// fresh internal module, and every `App` hides a domain/argument wiring
+/// that never reaches the final interface.
+struct Ctx {
+    internals: IndexMap<String, VModule>,
+    connections: Vec<VConn>,
+    next_id: usize,
+}
+
+impl Ctx {
+    fn new() -> Self {
+        Ctx {
+            internals: IndexMap::new(),
+            connections: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    fn fresh_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn instantiate(&mut self, base_name: &str, module: VModule) -> String {
+        let inst = format!("{}{}", base_name, self.fresh_id());
+        self.internals.insert(inst.clone(), module);
+        inst
+    }
+}
+
+/// Compiles `term` -- which must have type `ty` -- against `sig`, producing
+/// a single flat `VModule::Internal` named `name` ready for
+/// `generate_module_decl`. `Prim` becomes a leaf module instance; `Var`
+/// gets no instance of its own and reuses the ports its binding `Lam`
+/// allocated. `Prod` is the disjoint union of its arenas, `Lam` re-associates ports
+/// (its domain becomes a slice of the result's own interface), and `App`
+/// composes by instantiating both sides and wiring the function's domain
+/// to the argument through connections that are never exposed. `term` is
+/// first run through [`crate::interaction_net::normalize`].
+pub fn compile(term: &Term, ty: &Type, name: &str, sig: &Signature) -> Result<VModule, TypeError> {
+    // Beta-reduce first: `infer_compile` has no rule for a `Lam` in
+    // function position, so `(\x. body) arg` only compiles once reduced.
+    // Reduction drops an argument whose variable never occurs, and copies
+    // one whose variable occurs several times into each occurrence, so
+    // each copy is then instantiated separately.
+    let term = normalize(term);
+    let mut ctx = Ctx::new();
+    let venv = VarEnv::new();
+    let locs = check_compile(&term, ty, &venv, &mut ctx, sig)?;
+    Ok(finalize(name, ty, locs, ctx))
+}
+
+/// Mirrors [`crate::types::infer`], additionally returning where each of the
+/// term's moves is realized.
+fn infer_compile(
+    term: &Term,
+    venv: &VarEnv,
+    ctx: &mut Ctx,
+    sig: &Signature,
+) -> Result<(Type, Vec<VPortLoc>), TypeError> {
+    match term {
+        Term::Var(x) => venv
+            .get(x)
+            .cloned()
+            .ok_or_else(|| TypeError::UnboundVar(x.clone())),
+        Term::Prim(name) => {
+            let prim = sig
+                .get(name)
+                .ok_or_else(|| TypeError::UnknownPrim(name.clone()))?
+                .clone();
+            let inst = ctx.instantiate(name, prim.module.clone());
+            let locs = prim
+                .module
+                .get_interfaces()
+                .keys()
+                .map(|port_name| VPortLoc::new(Some(inst.clone()), port_name.clone()))
+                .collect();
+            Ok((prim.ty, locs))
+        }
+        Term::Prod(a, b) => {
+            let (a_ty, mut a_locs) = infer_compile(a, venv, ctx, sig)?;
+            let (b_ty, b_locs) = infer_compile(b, venv, ctx, sig)?;
+            a_locs.extend(b_locs);
+            Ok((Type::Cross(Box::new(a_ty), Box::new(b_ty)), a_locs))
+        }
+        Term::App(f, x) => {
+            let (f_ty, f_locs) = infer_compile(f, venv, ctx, sig)?;
+            let (dom, cod) = match f_ty {
+                Type::Arrow(dom, cod) => (*dom, *cod),
+                other => return Err(TypeError::ExpectedArrow(other)),
+            };
+            let x_locs = check_compile(x, &dom, venv, ctx, sig)?;
+
+            let dom_len = moves(&dom).len();
+            let (f_dom_locs, f_cod_locs) = f_locs.split_at(dom_len);
+
+            // Composition-with-hiding: wire f's domain straight to x's
+            // arena through connections that are internal only -- they
+            // never become part of the result's own interface.
+            for (f_loc, x_loc) in f_dom_locs.iter().zip(x_locs.iter()) {
+                ctx.connections
+                    .push(VConn::new(x_loc.clone(), f_loc.clone(), 0));
+            }
+
+            Ok((cod, f_cod_locs.to_vec()))
+        }
+        Term::Lam(..) => Err(TypeError::CannotInferLambda),
+    }
+}
+
+/// Mirrors [`crate::types::check`]; see `infer_compile` for the synthesis
+/// half.
+fn check_compile(
+    term: &Term,
+    expected: &Type,
+    venv: &VarEnv,
+    ctx: &mut Ctx,
+    sig: &Signature,
+) -> Result<Vec<VPortLoc>, TypeError> {
+    match (term, expected) {
+        (Term::Lam(x, body), Type::Arrow(dom, cod)) => {
+            // The parameter's moves become a slice of *this* module's own
+            // interface (at `finalize` time they're flipped, like the rest
+            // of the domain, since `moves(Arrow(dom, cod))` already flips
+            // `dom`). Nothing needs to be wired here -- `body` just reads
+            // and writes these ports directly.
+            let id = ctx.fresh_id();
+            let dom_locs: Vec<VPortLoc> = moves(dom)
+                .into_iter()
+                .enumerate()
+                .map(|(i, _)| VPortLoc::new(None, format!("{}{}_{}", x, id, i)))
+                .collect();
+
+            let mut venv = venv.clone();
+            venv.insert(x.clone(), ((**dom).clone(), dom_locs.clone()));
+
+            let mut locs = dom_locs;
+            locs.extend(check_compile(body, cod, &venv, ctx, sig)?);
+            Ok(locs)
+        }
+        (Term::Lam(_, _), other) => Err(TypeError::ExpectedArrow(other.clone())),
+        _ => {
+            let (found, locs) = infer_compile(term, venv, ctx, sig)?;
+            if &found == expected {
+                Ok(locs)
+            } else {
+                Err(TypeError::Mismatch {
+                    expected: expected.clone(),
+                    found,
+                })
+            }
+        }
+    }
+}
+
+/// Surfaces the root term's moves as the module's own interface: a move
+/// already realized on a `None`-located (i.e. already-top-level) port is
+/// declared as-is; one realized on an internal instance is given a fresh
+/// top-level name and wired through.
+fn finalize(name: &str, ty: &Type, locs: Vec<VPortLoc>, ctx: Ctx) -> VModule {
+    let root_moves = moves(ty);
+    let mut interfaces = IndexMap::new();
+    let mut connections = ctx.connections;
+
+    for (i, ((polarity, bits), loc)) in root_moves.iter().zip(locs.iter()).enumerate() {
+        match &loc.mod_name {
+            None => {
+                interfaces.insert(loc.port_name.clone(), VPort::new(polarity.clone(), *bits));
+            }
+            Some(_) => {
+                let top_name = format!("out_{}", i);
+                interfaces.insert(top_name.clone(), VPort::new(polarity.clone(), *bits));
+                let top_loc = VPortLoc::new(None, top_name);
+                connections.push(match polarity {
+                    Polarity::Output => VConn::new(loc.clone(), top_loc, *bits),
+                    Polarity::Input => VConn::new(top_loc, loc.clone(), *bits),
+                });
+            }
+        }
+    }
+
+    VModule::Internal {
+        name: name.to_string(),
+        interfaces,
+        internals: ctx.internals,
+        connections,
+    }
+}
+
+mod test_compiler {
+    use super::*;
+    use crate::backend::{generate_module_decl, VerilogBackend};
+
+    fn s<T: ToString>(s: T) -> String {
+        s.to_string()
+    }
+
+    // A one-primitive signature: `inc : Exp 8 -> Exp 8`, realized by an
+    // external module whose ports follow `moves(Exp 8 -> Exp 8)` in order:
+    // the (flipped) domain's request/answer, then the codomain's.
+    fn signature() -> Signature {
+        let inc = VModule::External {
+            name: s("inc"),
+            param: 8.into(),
+            interfaces: vec![
+                (s("arg_req"), VPort::new(Polarity::Output, 1)),
+                (s("arg_ans"), VPort::new(Polarity::Input, 8)),
+                (s("req"), VPort::new(Polarity::Input, 1)),
+                (s("ans"), VPort::new(Polarity::Output, 8)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        vec![(
+            s("inc"),
+            PrimSig {
+                ty: Type::Arrow(Box::new(Type::Exp(8)), Box::new(Type::Exp(8))),
+                module: inc,
+            },
+        )]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_compile_app() {
+        let sig = signature();
+        // \x. inc (inc x) : Exp 8 -> Exp 8
+        let term = Term::Lam(
+            s("x"),
+            Box::new(Term::App(
+                Box::new(Term::Prim(s("inc"))),
+                Box::new(Term::App(
+                    Box::new(Term::Prim(s("inc"))),
+                    Box::new(Term::Var(s("x"))),
+                )),
+            )),
+        );
+        let ty = Type::Arrow(Box::new(Type::Exp(8)), Box::new(Type::Exp(8)));
+
+        let vmod = compile(&term, &ty, "double_inc", &sig).expect("well-typed term");
+
+        let mut buf = Vec::<u8>::new();
+        generate_module_decl(&vmod, &VerilogBackend, &mut buf);
+        let s = buf.iter().map(|&u| u as char).collect::<String>();
+        println!("Generated verilog:\n{}", s)
+    }
+}
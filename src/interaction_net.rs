@@ -0,0 +1,448 @@
+use indexmap::map::IndexMap;
+
+use crate::syntax::{Term, Var};
+
+/// One of a node's ports: its principal port (the one that drives
+/// reduction when wired to another node's principal port) or one of its
+/// two auxiliary ports. `Epsilon` and `Prim` nodes only ever use
+/// `Principal`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Port {
+    Principal(NodeId),
+    Aux0(NodeId),
+    Aux1(NodeId),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(usize);
+
+/// The three standard interaction-combinator symbols, plus two leaves
+/// (`Pair`, `Prim`) needed to carry the parts of `Term` that fall outside
+/// the pure lambda calculus (`Prod`, `Prim`) through the net unreduced.
+/// Only `Gamma`, `Delta` and `Epsilon` ever take part in a reduction --
+/// `Pair` and `Prim` are opaque data as far as this IR is concerned.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Symbol {
+    /// Constructor: encodes both `Lam` (entered via its principal port,
+    /// aux0 the binder, aux1 the body) and `App` (entered via aux1, its
+    /// principal the function, aux0 the argument).
+    Gamma,
+    /// Duplicator: fans a shared variable out to its occurrences.
+    Delta,
+    /// Eraser: discards an unused variable (or, after a reduction,
+    /// whatever a duplicator/constructor it meets used to guard).
+    Epsilon,
+    /// A `Prod` pair: aux0 its first component, aux1 its second.
+    Pair,
+    /// A `Term::Prim` leaf, carried through unreduced.
+    Prim(String),
+    /// Marks the term's own value port, which (unlike every other port)
+    /// has no consumer of its own within the net. Wiring it to a `Root`
+    /// node's principal port, rather than leaving it dangling, lets
+    /// reduction touch it like any other port; `normalize` looks the
+    /// current root position back up through the marker afterwards.
+    Root,
+}
+
+fn is_reducible(symbol: &Symbol) -> bool {
+    matches!(symbol, Symbol::Gamma | Symbol::Delta | Symbol::Epsilon)
+}
+
+/// A net: a set of nodes plus a wiring relation pairing their ports
+/// (stored symmetrically, so `wires[p] == q` iff `wires[q] == p`).
+pub struct Net {
+    nodes: IndexMap<NodeId, Symbol>,
+    wires: IndexMap<Port, Port>,
+    next_id: usize,
+}
+
+impl Net {
+    fn new() -> Self {
+        Net {
+            nodes: IndexMap::new(),
+            wires: IndexMap::new(),
+            next_id: 0,
+        }
+    }
+
+    fn new_node(&mut self, symbol: Symbol) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        self.nodes.insert(id, symbol);
+        id
+    }
+
+    fn symbol(&self, id: NodeId) -> &Symbol {
+        self.nodes.get(&id).expect("dangling node id")
+    }
+
+    fn wire(&mut self, p: Port, q: Port) {
+        self.wires.insert(p, q);
+        self.wires.insert(q, p);
+    }
+
+    fn partner(&self, p: Port) -> Port {
+        *self
+            .wires
+            .get(&p)
+            .unwrap_or_else(|| panic!("port {:?} is not wired", p))
+    }
+
+    fn remove_node(&mut self, id: NodeId) {
+        for port in [Port::Principal(id), Port::Aux0(id), Port::Aux1(id)] {
+            if let Some(partner) = self.wires.shift_remove(&port) {
+                self.wires.shift_remove(&partner);
+            }
+        }
+        self.nodes.shift_remove(&id);
+    }
+
+    /// Finds a pair of nodes whose principal ports are wired together --
+    /// an active pair ready to fire. Only `Gamma`/`Delta`/`Epsilon` ever
+    /// match: a `Pair`/`Prim` leaf meeting a principal port is data, not a
+    /// redex, and is left alone.
+    fn find_redex(&self) -> Option<(NodeId, NodeId)> {
+        for (&id, symbol) in &self.nodes {
+            if !is_reducible(symbol) {
+                continue;
+            }
+            if let Some(&Port::Principal(other)) = self.wires.get(&Port::Principal(id)) {
+                if other != id && is_reducible(self.symbol(other)) {
+                    return Some((id, other));
+                }
+            }
+        }
+        None
+    }
+
+    /// Fires the active pair `(a, b)`, whose principal ports are wired
+    /// together.
+    fn step(&mut self, a: NodeId, b: NodeId) {
+        let (sa, sb) = (self.symbol(a).clone(), self.symbol(b).clone());
+        if sa == sb {
+            self.annihilate(a, b);
+        } else if sa == Symbol::Epsilon {
+            self.erase(a, b);
+        } else if sb == Symbol::Epsilon {
+            self.erase(b, a);
+        } else {
+            self.commute(a, sa, b, sb);
+        }
+    }
+
+    /// Equal symbols: connect the two nodes' aux ports pairwise and
+    /// delete both. For two erasers there are no aux ports to reconnect.
+    fn annihilate(&mut self, a: NodeId, b: NodeId) {
+        if self.symbol(a) == &Symbol::Epsilon {
+            self.remove_node(a);
+            self.remove_node(b);
+            return;
+        }
+        let a0 = self.partner(Port::Aux0(a));
+        let a1 = self.partner(Port::Aux1(a));
+        let b0 = self.partner(Port::Aux0(b));
+        let b1 = self.partner(Port::Aux1(b));
+        self.remove_node(a);
+        self.remove_node(b);
+
+        // An eta-shaped node (e.g. the identity `\x.x`, whose binder and
+        // body aux ports are wired straight to each other) has no outside
+        // connection of its own to offer -- the other side's two
+        // connections just pass through to each other instead.
+        if a0 == Port::Aux1(a) {
+            self.wire(b0, b1);
+        } else if b0 == Port::Aux1(b) {
+            self.wire(a0, a1);
+        } else {
+            self.wire(a0, b0);
+            self.wire(a1, b1);
+        }
+    }
+
+    /// `eps`'s principal meets `other`'s (a `Gamma` or `Delta`, since
+    /// erasers only reach here via `step`): the eraser propagates onto
+    /// each of `other`'s former aux partners, and both original nodes
+    /// vanish.
+    fn erase(&mut self, eps: NodeId, other: NodeId) {
+        let a0 = self.partner(Port::Aux0(other));
+        let a1 = self.partner(Port::Aux1(other));
+        self.remove_node(eps);
+        self.remove_node(other);
+        for p in [a0, a1] {
+            let e = self.new_node(Symbol::Epsilon);
+            self.wire(Port::Principal(e), p);
+        }
+    }
+
+    /// Distinct (reducible) symbols: each node is duplicated and the four
+    /// copies rewired crosswise, the standard interaction-combinator
+    /// commutation.
+    ///
+    /// Note: unlike `annihilate`, this doesn't special-case an eta-shaped
+    /// operand (one whose own two aux ports loop back to each other) --
+    /// such a node can only arise from a `Lam` that just returns its
+    /// argument unchanged, and this IR doesn't yet need to duplicate one.
+    fn commute(&mut self, a: NodeId, sa: Symbol, b: NodeId, sb: Symbol) {
+        let a0 = self.partner(Port::Aux0(a));
+        let a1 = self.partner(Port::Aux1(a));
+        let b0 = self.partner(Port::Aux0(b));
+        let b1 = self.partner(Port::Aux1(b));
+        self.remove_node(a);
+        self.remove_node(b);
+
+        let a1n = self.new_node(sa.clone());
+        let a2n = self.new_node(sa);
+        let b1n = self.new_node(sb.clone());
+        let b2n = self.new_node(sb);
+
+        self.wire(Port::Principal(a1n), b0);
+        self.wire(Port::Principal(a2n), b1);
+        self.wire(Port::Principal(b1n), a0);
+        self.wire(Port::Principal(b2n), a1);
+
+        self.wire(Port::Aux0(a1n), Port::Aux0(b1n));
+        self.wire(Port::Aux1(a1n), Port::Aux0(b2n));
+        self.wire(Port::Aux0(a2n), Port::Aux1(b1n));
+        self.wire(Port::Aux1(a2n), Port::Aux1(b2n));
+    }
+}
+
+/// Reduces `net` to normal form by repeatedly firing active pairs. May not
+/// terminate for a non-normalizing term, same as beta reduction itself.
+fn reduce(net: &mut Net) {
+    while let Some((a, b)) = net.find_redex() {
+        net.step(a, b);
+    }
+}
+
+/// Builds the fan-out tree for a variable with `count >= 2` occurrences: a
+/// binary tree of `Delta` nodes whose root is wired to the binder and
+/// whose `count` leaves are handed out to the occurrences in order.
+fn fanout(net: &mut Net, count: usize) -> (Port, Vec<Port>) {
+    let d = net.new_node(Symbol::Delta);
+    let root = Port::Principal(d);
+    let mut leaves = Vec::with_capacity(count);
+
+    let left_count = count / 2;
+    let right_count = count - left_count;
+
+    if left_count == 1 {
+        leaves.push(Port::Aux0(d));
+    } else {
+        let (left_root, left_leaves) = fanout(net, left_count);
+        net.wire(Port::Aux0(d), left_root);
+        leaves.extend(left_leaves);
+    }
+
+    if right_count == 1 {
+        leaves.push(Port::Aux1(d));
+    } else {
+        let (right_root, right_leaves) = fanout(net, right_count);
+        net.wire(Port::Aux1(d), right_root);
+        leaves.extend(right_leaves);
+    }
+
+    (root, leaves)
+}
+
+/// Ports still waiting to be handed to an occurrence of each bound
+/// variable, consumed left-to-right as `compile_rec` walks the body.
+type OccEnv = IndexMap<Var, Vec<Port>>;
+
+fn occurrences(term: &Term, x: &Var) -> usize {
+    match term {
+        Term::Var(y) => usize::from(y == x),
+        Term::Lam(y, body) => {
+            if y == x {
+                0
+            } else {
+                occurrences(body, x)
+            }
+        }
+        Term::App(f, a) => occurrences(f, x) + occurrences(a, x),
+        Term::Prod(a, b) => occurrences(a, x) + occurrences(b, x),
+        Term::Prim(_) => 0,
+    }
+}
+
+fn compile_rec(net: &mut Net, term: &Term, env: &mut OccEnv) -> Port {
+    match term {
+        Term::Var(x) => env
+            .get_mut(x)
+            .and_then(|occs| occs.pop())
+            .unwrap_or_else(|| panic!("variable `{}` used more times than it occurs", x)),
+        Term::Prim(name) => Port::Principal(net.new_node(Symbol::Prim(name.clone()))),
+        Term::Lam(x, body) => {
+            let l = net.new_node(Symbol::Gamma);
+            let binder = Port::Aux0(l);
+            match occurrences(body, x) {
+                0 => {
+                    let e = net.new_node(Symbol::Epsilon);
+                    net.wire(binder, Port::Principal(e));
+                }
+                1 => {
+                    // No duplicator needed: the one occurrence below will
+                    // consume `binder` directly.
+                    env.entry(x.clone()).or_default().push(binder);
+                }
+                n => {
+                    let (root, leaves) = fanout(net, n);
+                    net.wire(binder, root);
+                    // `compile_rec` pops from the back, so reverse to hand
+                    // occurrences out in left-to-right order.
+                    let mut leaves = leaves;
+                    leaves.reverse();
+                    env.entry(x.clone()).or_default().extend(leaves);
+                }
+            }
+            let body_port = compile_rec(net, body, env);
+            net.wire(Port::Aux1(l), body_port);
+            Port::Principal(l)
+        }
+        Term::App(f, a) => {
+            let n = net.new_node(Symbol::Gamma);
+            let f_port = compile_rec(net, f, env);
+            net.wire(Port::Principal(n), f_port);
+            let a_port = compile_rec(net, a, env);
+            net.wire(Port::Aux0(n), a_port);
+            Port::Aux1(n)
+        }
+        Term::Prod(a, b) => {
+            let p = net.new_node(Symbol::Pair);
+            let a_port = compile_rec(net, a, env);
+            net.wire(Port::Aux0(p), a_port);
+            let b_port = compile_rec(net, b, env);
+            net.wire(Port::Aux1(p), b_port);
+            Port::Principal(p)
+        }
+    }
+}
+
+/// Compiles `term` into a net together with the `Root` marker node whose
+/// principal port is wired to the port currently carrying the term's
+/// value.
+fn compile(term: &Term) -> (Net, NodeId) {
+    let mut net = Net::new();
+    let mut env = OccEnv::new();
+    let value = compile_rec(&mut net, term, &mut env);
+    let root = net.new_node(Symbol::Root);
+    net.wire(Port::Principal(root), value);
+    (net, root)
+}
+
+/// Resolves `port` to the name of the variable it carries, if it is (or
+/// is a fan-out copy of) some currently-open `Lam`'s binder port. Since
+/// `Delta` nodes are only ever introduced by `fanout` to share a bound
+/// variable (or by `commute`, as copies of such a `Delta`), chasing a `Delta`'s principal backwards always lands on the
+/// binder it duplicates -- an invariant that survives reduction, unlike
+/// the shape of the fan-out tree itself.
+fn resolve_var(net: &Net, port: Port, var_of_binder: &IndexMap<Port, Var>) -> Option<Var> {
+    if let Some(x) = var_of_binder.get(&port) {
+        return Some(x.clone());
+    }
+    match port {
+        Port::Aux0(d) | Port::Aux1(d) if net.symbol(d) == &Symbol::Delta => {
+            resolve_var(net, net.partner(Port::Principal(d)), var_of_binder)
+        }
+        _ => None,
+    }
+}
+
+/// Walks the (normal-form) net back to a `Term`, starting from `port`.
+fn readback(net: &Net, port: Port, var_of_binder: &IndexMap<Port, Var>) -> Term {
+    if let Some(x) = resolve_var(net, port, var_of_binder) {
+        return Term::Var(x);
+    }
+    match port {
+        Port::Principal(id) => match net.symbol(id) {
+            Symbol::Gamma => {
+                let x = format!("v{}", id.0);
+                let mut var_of_binder = var_of_binder.clone();
+                var_of_binder.insert(Port::Aux0(id), x.clone());
+                let body = readback(net, net.partner(Port::Aux1(id)), &var_of_binder);
+                Term::Lam(x, Box::new(body))
+            }
+            Symbol::Pair => {
+                let a = readback(net, net.partner(Port::Aux0(id)), var_of_binder);
+                let b = readback(net, net.partner(Port::Aux1(id)), var_of_binder);
+                Term::Prod(Box::new(a), Box::new(b))
+            }
+            Symbol::Prim(name) => Term::Prim(name.clone()),
+            Symbol::Epsilon | Symbol::Delta | Symbol::Root => {
+                panic!("port {:?} is an eraser/duplicator/root marker, not a value", port)
+            }
+        },
+        Port::Aux1(id) if net.symbol(id) == &Symbol::Gamma => {
+            // Entered via aux1: this `Gamma` plays an `App`, not a `Lam`
+            // -- its principal is the function, its aux0 the argument.
+            let f = readback(net, net.partner(Port::Principal(id)), var_of_binder);
+            let a = readback(net, net.partner(Port::Aux0(id)), var_of_binder);
+            Term::App(Box::new(f), Box::new(a))
+        }
+        Port::Aux0(d) | Port::Aux1(d) if net.symbol(d) == &Symbol::Delta => {
+            // Not a live variable occurrence (`resolve_var` already would
+            // have caught that): reduction has substituted a concrete
+            // value in at the binder, and this `Delta` is duplicating it
+            // -- read the value back once per occurrence reached here.
+            readback(net, net.partner(Port::Principal(d)), var_of_binder)
+        }
+        _ => panic!("port {:?} is not a readable value position", port),
+    }
+}
+
+/// Replaces `term` with its normal form, resolving variable sharing and
+/// dead subterms via interaction-net reduction before the denotational
+/// compiler (`crate::compiler::compile`) runs.
+pub fn normalize(term: &Term) -> Term {
+    let (mut net, root) = compile(term);
+    reduce(&mut net);
+    let value = net.partner(Port::Principal(root));
+    readback(&net, value, &IndexMap::new())
+}
+
+mod test_interaction_net {
+    use super::*;
+
+    fn s<T: ToString>(s: T) -> String {
+        s.to_string()
+    }
+
+    #[test]
+    fn test_normalize_identity() {
+        // (\x. x) a  ~>  a
+        let term = Term::App(
+            Box::new(Term::Lam(s("x"), Box::new(Term::Var(s("x"))))),
+            Box::new(Term::Prim(s("a"))),
+        );
+        let normal = normalize(&term);
+        println!("{:?}", normal);
+    }
+
+    #[test]
+    fn test_normalize_sharing() {
+        // (\x. (x, x)) a  ~>  (a, a)
+        let term = Term::App(
+            Box::new(Term::Lam(
+                s("x"),
+                Box::new(Term::Prod(
+                    Box::new(Term::Var(s("x"))),
+                    Box::new(Term::Var(s("x"))),
+                )),
+            )),
+            Box::new(Term::Prim(s("a"))),
+        );
+        let normal = normalize(&term);
+        println!("{:?}", normal);
+    }
+
+    #[test]
+    fn test_normalize_unused() {
+        // (\x. a) b  ~>  a
+        let term = Term::App(
+            Box::new(Term::Lam(s("x"), Box::new(Term::Prim(s("a"))))),
+            Box::new(Term::Prim(s("b"))),
+        );
+        let normal = normalize(&term);
+        println!("{:?}", normal);
+    }
+}
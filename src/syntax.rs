@@ -1,9 +1,10 @@
 pub type Var = String;
 
+#[derive(Clone, Debug)]
 pub enum Term {
-    Var (Var),
-    Lam (Var, Term),
-    App (Term, Term),
-    Prod (Term, Term),
-    Prim (String),
+    Var(Var),
+    Lam(Var, Box<Term>),
+    App(Box<Term>, Box<Term>),
+    Prod(Box<Term>, Box<Term>),
+    Prim(String),
 }
@@ -1,9 +1,135 @@
+use std::fmt;
+
 use indexmap::map::IndexMap;
 
-enum Type {
+use crate::syntax::{Term, Var};
+use crate::verilog_ir::Polarity;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Type {
+    /// A command: request it, get told it is done. `[|Com|] = (O,1), (P,1)`.
     Com,
-    Exp,
+    /// A value-returning expression of the given bit width: request it, get
+    /// the value back. `[|Exp n|] = (O,1), (P,n)`.
+    Exp(usize),
+    /// A type variable, e.g. for a primitive whose width is inherited from
+    /// its enclosing module rather than fixed at the type level.
     Var,
-    Cross (Type, Type),
-    Arrow (Type, Type),
+    Cross(Box<Type>, Box<Type>),
+    Arrow(Box<Type>, Box<Type>),
+}
+
+pub type TypeEnv = IndexMap<Var, Type>;
+
+#[derive(Clone, Debug)]
+pub enum TypeError {
+    UnboundVar(Var),
+    UnknownPrim(String),
+    ExpectedArrow(Type),
+    Mismatch { expected: Type, found: Type },
+    CannotInferLambda,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeError::UnboundVar(x) => write!(f, "unbound variable `{}`", x),
+            TypeError::UnknownPrim(name) => write!(f, "unknown primitive `{}`", name),
+            TypeError::ExpectedArrow(ty) => {
+                write!(f, "expected a function type, found {:?}", ty)
+            }
+            TypeError::Mismatch { expected, found } => {
+                write!(f, "expected type {:?}, found {:?}", expected, found)
+            }
+            TypeError::CannotInferLambda => write!(
+                f,
+                "cannot infer the type of a lambda; it must be checked against an expected type"
+            ),
+        }
+    }
+}
+
+/// Synthesizes a `Type` for `term`, which must carry enough information on
+/// its own (variables, applications, primitives, pairs) -- an un-annotated
+/// `Lam` cannot be inferred and must instead be checked against an expected
+/// type with [`check`].
+pub fn infer(term: &Term, env: &TypeEnv, sig: &IndexMap<String, Type>) -> Result<Type, TypeError> {
+    match term {
+        Term::Var(x) => env
+            .get(x)
+            .cloned()
+            .ok_or_else(|| TypeError::UnboundVar(x.clone())),
+        Term::Prim(name) => sig
+            .get(name)
+            .cloned()
+            .ok_or_else(|| TypeError::UnknownPrim(name.clone())),
+        Term::Prod(a, b) => Ok(Type::Cross(
+            Box::new(infer(a, env, sig)?),
+            Box::new(infer(b, env, sig)?),
+        )),
+        Term::App(f, x) => match infer(f, env, sig)? {
+            Type::Arrow(dom, cod) => {
+                check(x, &dom, env, sig)?;
+                Ok(*cod)
+            }
+            other => Err(TypeError::ExpectedArrow(other)),
+        },
+        Term::Lam(..) => Err(TypeError::CannotInferLambda),
+    }
+}
+
+/// Analyzes `term` against `expected`. The only rule that isn't "infer and
+/// compare" is `Lam`, which needs the expected `Arrow` to know its
+/// parameter's type.
+pub fn check(
+    term: &Term,
+    expected: &Type,
+    env: &TypeEnv,
+    sig: &IndexMap<String, Type>,
+) -> Result<(), TypeError> {
+    match (term, expected) {
+        (Term::Lam(x, body), Type::Arrow(dom, cod)) => {
+            let mut env = env.clone();
+            env.insert(x.clone(), (**dom).clone());
+            check(body, cod, &env, sig)
+        }
+        (Term::Lam(_, _), other) => Err(TypeError::ExpectedArrow(other.clone())),
+        _ => {
+            let found = infer(term, env, sig)?;
+            if &found == expected {
+                Ok(())
+            } else {
+                Err(TypeError::Mismatch {
+                    expected: expected.clone(),
+                    found,
+                })
+            }
+        }
+    }
+}
+
+/// The arena denotation `[|T|]`: an ordered list of moves, each a
+/// `(Polarity, bits)` pair. Opponent moves (`O`) are `Input`, Player moves
+/// (`P`) are `Output` -- `Arrow(A, B)`'s domain is on the "wrong side" of
+/// the turnstile, so its moves are flipped before being prepended to `B`'s.
+pub fn moves(ty: &Type) -> Vec<(Polarity, usize)> {
+    match ty {
+        Type::Com => vec![(Polarity::Input, 1), (Polarity::Output, 1)],
+        Type::Exp(n) => vec![(Polarity::Input, 1), (Polarity::Output, *n)],
+        Type::Var => panic!("Type::Var has no arena of its own; it must be resolved to a ground type before denotation"),
+        Type::Cross(a, b) => {
+            let mut ms = moves(a);
+            ms.extend(moves(b));
+            ms
+        }
+        Type::Arrow(a, b) => {
+            let mut ms = flip_moves(&moves(a));
+            ms.extend(moves(b));
+            ms
+        }
+    }
+}
+
+fn flip_moves(ms: &[(Polarity, usize)]) -> Vec<(Polarity, usize)> {
+    ms.iter().map(|(p, bits)| (p.flip(), *bits)).collect()
 }
@@ -1,6 +1,3 @@
-use std::cell::RefCell;
-use std::io;
-
 use indexmap::map::IndexMap;
 
 #[derive(Clone, PartialEq)]
@@ -9,32 +6,163 @@ pub enum Polarity {
     Output,
 }
 
-#[derive(new, Clone)]
+impl Polarity {
+    pub(crate) fn flip(&self) -> Polarity {
+        match self {
+            Polarity::Input => Polarity::Output,
+            Polarity::Output => Polarity::Input,
+        }
+    }
+}
+
+/// A bit width: a named module parameter, an integer literal, or an
+/// arithmetic combination of either -- so a `VModule::External` can be
+/// declared once as width-polymorphic (its ports' widths referencing a
+/// `Param`) and instantiated at several concrete widths, instead of being
+/// monomorphized in Rust before emission.
+#[derive(Clone, PartialEq, Debug)]
+pub enum WidthExpr {
+    Const(usize),
+    Param(String),
+    Add(Box<WidthExpr>, Box<WidthExpr>),
+    Mul(Box<WidthExpr>, Box<WidthExpr>),
+    Max(Box<WidthExpr>, Box<WidthExpr>),
+}
+
+impl From<usize> for WidthExpr {
+    fn from(n: usize) -> Self {
+        WidthExpr::Const(n)
+    }
+}
+
+impl WidthExpr {
+    /// Renders as a Verilog expression: a bare literal/identifier, or a
+    /// parenthesized arithmetic expression.
+    pub(crate) fn render(&self) -> String {
+        match self {
+            WidthExpr::Const(n) => n.to_string(),
+            WidthExpr::Param(name) => name.clone(),
+            WidthExpr::Add(a, b) => format!("({} + {})", a.render(), b.render()),
+            WidthExpr::Mul(a, b) => format!("({} * {})", a.render(), b.render()),
+            WidthExpr::Max(a, b) => format!(
+                "(({} > {}) ? {} : {})",
+                a.render(),
+                b.render(),
+                a.render(),
+                b.render()
+            ),
+        }
+    }
+
+    /// Appends the names of the parameters it references, in
+    /// first-occurrence order, skipping ones already in `out`.
+    pub(crate) fn free_params(&self, out: &mut Vec<String>) {
+        match self {
+            WidthExpr::Const(_) => {}
+            WidthExpr::Param(name) => {
+                if !out.contains(name) {
+                    out.push(name.clone());
+                }
+            }
+            WidthExpr::Add(a, b) | WidthExpr::Mul(a, b) | WidthExpr::Max(a, b) => {
+                a.free_params(out);
+                b.free_params(out);
+            }
+        }
+    }
+}
+
+/// The shape of a `VPort`: either a single wire, or an aggregate built out
+/// of single wires.
+///
+/// Game-semantics arenas (e.g. a `Cross` of two sub-arenas, or a
+/// request/answer pair) are naturally structured records rather than one
+/// flat bitvector, so ports need to express that structure instead of
+/// forcing every caller to hand-flatten it into a single width.
+#[derive(Clone)]
+pub enum PortType {
+    Leaf {
+        polarity: Polarity,
+        bits: WidthExpr,
+    },
+    /// A named record of sub-fields, e.g. `{ req: Output(1), resp: Input(1) }`.
+    Bundle(IndexMap<String, PortType>),
+    /// `len` copies of `elem`, indexed `0..len`.
+    Vector {
+        elem: Box<PortType>,
+        len: usize,
+    },
+    /// `inner` with every leaf polarity reversed -- a bidirectional bundle
+    /// field whose data flows the opposite way from its siblings.
+    Flip(Box<PortType>),
+}
+
+#[derive(Clone)]
 pub struct VPort {
-    polarity: Polarity,
-    bits: usize,
+    pub(crate) ty: PortType,
+}
+
+impl VPort {
+    /// A single flat wire -- what every `VPort` was before aggregate types
+    /// existed, and still the common case.
+    pub fn new(polarity: Polarity, bits: impl Into<WidthExpr>) -> Self {
+        VPort {
+            ty: PortType::Leaf {
+                polarity,
+                bits: bits.into(),
+            },
+        }
+    }
+
+    /// A bundle, vector, or flipped port, for arenas whose denotation is
+    /// naturally a structured record rather than one flat wire.
+    pub fn aggregate(ty: PortType) -> Self {
+        VPort { ty }
+    }
+
+    pub(crate) fn polarity(&self) -> &Polarity {
+        match &self.ty {
+            PortType::Leaf { polarity, .. } => polarity,
+            _ => panic!("polarity() only applies to a flattened leaf port"),
+        }
+    }
+
+    pub(crate) fn bits(&self) -> &WidthExpr {
+        match &self.ty {
+            PortType::Leaf { bits, .. } => bits,
+            _ => panic!("bits() only applies to a flattened leaf port"),
+        }
+    }
+
+    pub(crate) fn flipped(&self) -> VPort {
+        match &self.ty {
+            PortType::Leaf { polarity, bits } => VPort::new(polarity.flip(), bits.clone()),
+            _ => panic!("flipped() only applies to a flattened leaf port"),
+        }
+    }
 }
 
 #[derive(Clone, new, PartialEq, Eq, Hash, Debug)]
 pub struct VPortLoc {
     // None means this module
-    mod_name: Option<String>,
-    port_name: String,
+    pub(crate) mod_name: Option<String>,
+    pub(crate) port_name: String,
 }
 
 #[derive(new, Clone)]
 pub struct VConn {
-    src: VPortLoc,
-    dst: VPortLoc,
-    bits: usize,
+    pub(crate) src: VPortLoc,
+    pub(crate) dst: VPortLoc,
+    #[new(into)]
+    pub(crate) bits: WidthExpr,
 }
 
 #[derive(Clone)]
 pub enum VModule {
     External {
         name: String,
-        // bitwidth (TODO: support more generic parameters)
-        param: usize,
+        // bitwidth, width-polymorphic when this is a `Param`
+        param: WidthExpr,
         interfaces: IndexMap<String, VPort>,
     },
     Internal {
@@ -46,14 +174,14 @@ pub enum VModule {
 }
 
 impl VModule {
-    fn get_name(&self) -> &str {
+    pub(crate) fn get_name(&self) -> &str {
         match self {
             VModule::External { name, .. } => name,
             VModule::Internal { name, .. } => name,
         }
     }
 
-    fn get_interfaces(&self) -> &IndexMap<String, VPort> {
+    pub(crate) fn get_interfaces(&self) -> &IndexMap<String, VPort> {
         match self {
             VModule::External { interfaces, .. } => interfaces,
             VModule::Internal { interfaces, .. } => interfaces,
@@ -61,251 +189,60 @@ impl VModule {
     }
 }
 
-fn generate_wire_name(input: &VPortLoc, output: &VPortLoc) -> String {
-    // If one of the port is a module interface, use it.
-    if let None = &input.mod_name {
-        return input.port_name.clone();
+fn join_suffix(head: &str, rest: &str) -> String {
+    if rest.is_empty() {
+        head.to_string()
+    } else {
+        format!("{}_{}", head, rest)
     }
-    if let None = &output.mod_name {
-        return output.port_name.clone();
-    }
-
-    // None cases are covered by above code
-    let input_mod_name = input.mod_name.as_ref().unwrap();
-    let output_mod_name = output.mod_name.as_ref().unwrap();
-
-    format!(
-        "{}_{}_{}_{}",
-        input_mod_name, input.port_name, output_mod_name, output.port_name
-    )
 }
 
-pub fn generate_module_decl<T: io::Write>(vmod: &VModule, defs: &mut T) {
-    let cur_tab = RefCell::new(0);
-
-    let open_scope = || {
-        *cur_tab.borrow_mut() += 4;
-    };
-
-    let close_scope = || {
-        *cur_tab.borrow_mut() -= 4;
-    };
-
-    macro_rules! gen {
-        ( $stream:expr, $( $e:expr ),* ) => {
-            for _ in 0..*cur_tab.borrow() {
-                $stream.write(" ".as_bytes()).unwrap();
-            }
-            $stream.write(format!( $( $e ),* ).as_bytes()).unwrap();
-        };
-    }
-    macro_rules! genln {
-        ( $stream:expr, $( $e:expr ),* ) => {
-            gen!($stream, $($e),*);
-            $stream.write("\n".as_bytes()).unwrap();
-        };
-    }
-
-    match vmod {
-        VModule::External { .. } => panic!("generate_module_decl accepts only Internal module"),
-        VModule::Internal {
-            name,
-            interfaces,
-            internals,
-            connections,
-        } => {
-            genln!(defs, "module {} (", name);
-            {
-                open_scope();
-                let args = interfaces
-                    .iter()
-                    .map(|(name, _)| name.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ");
-                genln!(defs, "{}", args);
-                close_scope();
-            }
-            genln!(defs, ");");
-
-            {
-                open_scope();
-
-                // Generate port decls
-                for (name, port) in interfaces {
-                    let io = if port.polarity == Polarity::Input {
-                        "input"
-                    } else {
-                        "output"
-                    };
-
-                    let bitwidth = &if port.bits > 1 {
-                        format!("[{}:0]", port.bits - 1)
-                    } else {
-                        "".to_string()
-                    };
-
-                    genln!(defs, "{} {} {};", io, bitwidth, name);
-                }
-
-                // Create wire
-                // Wires
-                let mut wires = Vec::<(String, usize)>::new();
-                // Module name & port name -> wire name & bitwidth
-                let mut port_to_wire = IndexMap::<VPortLoc, (String, usize)>::new();
-                // assign dst = src;
-                let mut assigns = Vec::<(String, String)>::new();
-
-                for VConn { src, dst, bits } in connections {
-                    match (&src.mod_name, &dst.mod_name) {
-                        (None, None) => {
-                            // interface = interface
-                            assigns.push((dst.port_name.clone(), src.port_name.clone()))
-                        }
-                        (Some(mod_name), None) => {
-                            // interface = internal module port
-                            let wire_name = format!("{}_{}", mod_name, src.port_name.clone());
-                            wires.push((wire_name.clone(), *bits));
-                            port_to_wire.insert(src.clone(), (wire_name.clone(), *bits));
-                            assigns.push((dst.port_name.clone(), wire_name.clone()))
-                        }
-                        (None, Some(mod_name)) => {
-                            // internal module port = interface
-                            let wire_name = format!("{}_{}", mod_name, dst.port_name.clone());
-                            wires.push((wire_name.clone(), *bits));
-                            port_to_wire.insert(dst.clone(), (wire_name.clone(), *bits));
-                            assigns.push((wire_name.clone(), src.port_name.clone()));
-                        }
-                        (Some(src_mod_name), Some(dst_mod_name)) => {
-                            let src_wire_name =
-                                format!("{}_{}", src_mod_name, src.port_name.clone());
-                            let dst_wire_name =
-                                format!("{}_{}", dst_mod_name, dst.port_name.clone());
-
-                            wires.push((src_wire_name.clone(), *bits));
-                            wires.push((src_wire_name.clone(), *bits));
-
-                            port_to_wire.insert(src.clone(), (src_wire_name.clone(), *bits));
-                            port_to_wire.insert(dst.clone(), (dst_wire_name.clone(), *bits));
-
-                            assigns.push((dst_wire_name.clone(), src_wire_name.clone()));
-                        }
-                    }
-                }
-
-                for (wire_name, bits) in &wires {
-                    let bitwidth = &if *bits > 1 {
-                        format!("[{}:0]", *bits - 1)
-                    } else {
-                        "".to_string()
-                    };
-
-                    genln!(defs, "wire {} {};", bitwidth, wire_name);
-                }
-
-                for (src, dst) in &assigns {
-                    genln!(defs, "assign {} = {};", src, dst);
-                }
-
-                for (name, vmod) in internals {
-                    let mod_name = vmod.get_name();
-
-                    let args = vmod
-                        .get_interfaces()
-                        .iter()
-                        .map(|(port_name, _)| {
-                            let loc = VPortLoc::new(Some(name.clone()), port_name.clone());
-                            let (wire_name, _) = port_to_wire
-                                .get(&loc)
-                                .expect(&format!("The port loc {:?} is not found", loc));
-                            wire_name.clone()
-                        })
-                        .collect::<Vec<_>>()
-                        .join(", ");
-
-                    genln!(defs, "{} {} ({}));\n", mod_name, name, args);
-                }
-
-                close_scope();
-            }
-
-            genln!(defs, "endmodule");
-        }
-    }
+/// Combines a port (or field) name with a (possibly empty) flattening
+/// suffix, using the `port_field_subfield` naming scheme.
+pub(crate) fn full_port_name(base: &str, suffix: &str) -> String {
+    join_suffix(base, suffix)
 }
 
-mod test_verilog_ir {
-    use super::*;
-
-    fn s<T: ToString>(s: T) -> String {
-        s.to_string()
-    }
-
-    #[test]
-    fn test_seq() {
-        let d_flip_flop = VModule::External {
-            name: s("d_flip_flop"),
-            param: 8,
-            interfaces: vec![
-                (s("in"), VPort::new(Polarity::Input, 8)),
-                (s("out"), VPort::new(Polarity::Output, 8)),
-            ]
+/// Recursively expands a (possibly aggregate) port type into its leaf
+/// wires, each tagged with the suffix identifying it within the port --
+/// empty for a plain `Leaf`, `"field"` / `"field_subfield"` for a `Bundle`,
+/// `"0"` / `"1"` / ... for a `Vector`.
+pub(crate) fn flatten_port_type(ty: &PortType) -> Vec<(String, VPort)> {
+    match ty {
+        PortType::Leaf { polarity, bits } => {
+            vec![(String::new(), VPort::new(polarity.clone(), bits.clone()))]
+        }
+        PortType::Flip(inner) => flatten_port_type(inner)
             .into_iter()
+            .map(|(suffix, port)| (suffix, port.flipped()))
             .collect(),
-        };
-
-        // seq: con * exp -> exp
-        // [| con |] = (+0, -0)
-        // [| exp |] = (+0, -n)
-        // [| con * exp -> exp |] = (-0, +0, -0, +n, +0, -n)
-        let vmod = VModule::Internal {
-            name: s("seq"),
-            interfaces: vec![
-                (s("cmd_req"), VPort::new(Polarity::Output, 1)),
-                (s("cmd_valid"), VPort::new(Polarity::Input, 1)),
-                (s("exp_req"), VPort::new(Polarity::Output, 1)),
-                (s("exp"), VPort::new(Polarity::Input, 8)),
-                (s("exp_valid"), VPort::new(Polarity::Input, 1)),
-                (s("req"), VPort::new(Polarity::Input, 1)),
-                (s("ret"), VPort::new(Polarity::Output, 8)),
-                (s("valid"), VPort::new(Polarity::Output, 1)),
-            ]
-            .into_iter()
+        PortType::Bundle(fields) => fields
+            .iter()
+            .flat_map(|(field, field_ty)| {
+                flatten_port_type(field_ty)
+                    .into_iter()
+                    .map(move |(suffix, port)| (join_suffix(field, &suffix), port))
+            })
+            .collect(),
+        PortType::Vector { elem, len } => (0..*len)
+            .flat_map(|i| {
+                flatten_port_type(elem)
+                    .into_iter()
+                    .map(move |(suffix, port)| (join_suffix(&i.to_string(), &suffix), port))
+            })
             .collect(),
-
-            internals: [("D".to_string(), d_flip_flop)].iter().cloned().collect(),
-
-            connections: vec![
-                VConn::new(
-                    VPortLoc::new(None, s("req")),
-                    VPortLoc::new(None, s("cmd_req")),
-                    1,
-                ),
-                VConn::new(
-                    VPortLoc::new(None, s("cmd_valid")),
-                    VPortLoc::new(Some(s("D")), s("in")),
-                    1,
-                ),
-                VConn::new(
-                    VPortLoc::new(Some(s("D")), s("out")),
-                    VPortLoc::new(None, s("exp_req")),
-                    1,
-                ),
-                VConn::new(
-                    VPortLoc::new(None, s("exp_valid")),
-                    VPortLoc::new(None, s("valid")),
-                    8,
-                ),
-                VConn::new(
-                    VPortLoc::new(None, s("exp")),
-                    VPortLoc::new(None, s("ret")),
-                    8,
-                ),
-            ],
-        };
-
-        let mut buf = Vec::<u8>::new();
-        generate_module_decl(&vmod, &mut buf);
-        let s = buf.iter().map(|&u| u as char).collect::<String>();
-        println!("Generated verilog:\n{}", s)
     }
 }
+
+/// Flattens every port of an interface map, already joined with its port
+/// name (`port_field_subfield`).
+pub(crate) fn flatten_interfaces(interfaces: &IndexMap<String, VPort>) -> Vec<(String, VPort)> {
+    interfaces
+        .iter()
+        .flat_map(|(name, port)| {
+            flatten_port_type(&port.ty)
+                .into_iter()
+                .map(move |(suffix, leaf)| (full_port_name(name, &suffix), leaf))
+        })
+        .collect()
+}